@@ -1,32 +1,144 @@
 use std::{
+    cell::{Cell, UnsafeCell},
     future::Future,
     io::{self, Read, Write},
+    marker::PhantomData,
     ops::{Deref, DerefMut},
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
 };
 
 use monoio::{
     io::{AsyncReadRent, AsyncWriteRent},
     BufResult,
 };
-use rustls::{ConnectionCommon, SideData};
+use rustls::{
+    Certificate, ClientConnection, ConnectionCommon, ProtocolVersion, ServerConnection, SideData,
+    WriteEarlyData,
+};
 
 use crate::unsafe_io::{UnsafeRead, UnsafeWrite};
 
+// Where a stream sits relative to TLS 1.3 0-RTT early data. `buf` mirrors every byte passed to
+// `write()` during this window; `offered` is the prefix of it that was actually handed to
+// rustls's early-data writer (and so must be skipped on accept, but replayed on reject like the
+// rest of `buf`). Bytes past `offered` were never offered at all (rustls stopped accepting 0-RTT
+// writes, e.g. the ticket's early-data cap was hit) and must always be replayed, accept or not.
+#[derive(Debug)]
+enum TlsState {
+    EarlyData {
+        pos: usize,
+        offered: usize,
+        buf: Vec<u8>,
+    },
+    Stream,
+    WriteShutdown,
+}
+
+// rustls only offers early data on the client side, so server sessions just report none.
+pub(crate) trait MaybeEarlyData {
+    fn early_data_writer(&mut self) -> Option<WriteEarlyData<'_>>;
+    fn is_early_data_accepted(&self) -> bool;
+}
+
+impl MaybeEarlyData for ClientConnection {
+    fn early_data_writer(&mut self) -> Option<WriteEarlyData<'_>> {
+        self.early_data()
+    }
+
+    fn is_early_data_accepted(&self) -> bool {
+        ClientConnection::is_early_data_accepted(self)
+    }
+}
+
+impl MaybeEarlyData for ServerConnection {
+    fn early_data_writer(&mut self) -> Option<WriteEarlyData<'_>> {
+        None
+    }
+
+    fn is_early_data_accepted(&self) -> bool {
+        false
+    }
+}
+
+// Who flushes a TLS alert that `read_io` raised while decoding incoming data: inline is fine
+// for an unsplit `Stream`, but split defers to the write half instead (see `Stream::split`).
+#[derive(Debug)]
+enum AlertFlush {
+    Inline,
+    Deferred(Rc<Cell<bool>>),
+}
+
 #[derive(Debug)]
 pub(crate) struct Stream<IO, C> {
     pub(crate) io: IO,
     pub(crate) session: C,
+    state: TlsState,
+    alert_flush: AlertFlush,
 }
 
 impl<IO, C> Stream<IO, C> {
     pub fn new(io: IO, session: C) -> Self {
-        Self { io, session }
+        Self {
+            io,
+            session,
+            state: TlsState::Stream,
+            alert_flush: AlertFlush::Inline,
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> (&IO, &C) {
+        (&self.io, &self.session)
+    }
+
+    pub(crate) fn get_mut(&mut self) -> (&mut IO, &mut C) {
+        (&mut self.io, &mut self.session)
+    }
+}
+
+impl<IO, C, SD: SideData + 'static> Stream<IO, C>
+where
+    C: Deref<Target = ConnectionCommon<SD>>,
+{
+    pub(crate) fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.session.alpn_protocol()
+    }
+
+    pub(crate) fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.session.protocol_version()
+    }
+
+    pub(crate) fn peer_certificates(&self) -> Option<&[Certificate]> {
+        self.session.peer_certificates()
+    }
+}
+
+impl<IO> Stream<IO, ServerConnection> {
+    pub(crate) fn sni_hostname(&self) -> Option<&str> {
+        self.session.sni_hostname()
+    }
+}
+
+impl<IO: AsyncReadRent + AsyncWriteRent> Stream<IO, ClientConnection> {
+    // Buffers the first bytes passed to `write()` as 0-RTT early data if the session has a
+    // usable resumption ticket.
+    pub(crate) fn new_with_early_data(io: IO, session: ClientConnection) -> Self {
+        let mut stream = Self::new(io, session);
+        if stream.session.early_data().is_some() {
+            stream.state = TlsState::EarlyData {
+                pos: 0,
+                offered: 0,
+                buf: Vec::new(),
+            };
+        }
+        stream
     }
 }
 
 impl<IO: AsyncReadRent + AsyncWriteRent, C, SD: SideData> Stream<IO, C>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<SD>>,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
 {
     pub(crate) async fn read_io(&mut self) -> io::Result<usize> {
         let mut unsafe_read = UnsafeRead::default();
@@ -47,9 +159,14 @@ where
         let state = match self.session.process_new_packets() {
             Ok(state) => state,
             Err(err) => {
-                // TODO(ihciah): when to write_io? If we do this in read call, the UnsafeWrite may crash
-                // when we impl split in an UnsafeCell way.
-                let _ = self.write_io().await;
+                match &self.alert_flush {
+                    AlertFlush::Inline => {
+                        let _ = self.write_io().await;
+                    }
+                    // Split: don't touch `io` here, the write half may be using it right now.
+                    // It will flush this alert itself the next time it runs.
+                    AlertFlush::Deferred(pending) => pending.set(true),
+                }
                 return Err(io::Error::new(io::ErrorKind::InvalidData, err));
             }
         };
@@ -88,10 +205,12 @@ where
         let mut rdlen = 0;
         let mut eof = false;
 
+        // Flush the ClientHello, carrying any early data already buffered into the session.
+        while self.session.wants_write() && self.session.is_handshaking() {
+            wrlen += self.write_io().await?;
+        }
+
         loop {
-            while self.session.wants_write() && self.session.is_handshaking() {
-                wrlen += self.write_io().await?;
-            }
             while !eof && self.session.wants_read() && self.session.is_handshaking() {
                 let n = self.read_io().await?;
                 rdlen += n;
@@ -100,6 +219,31 @@ where
                 }
             }
 
+            // Only once the server's first flight has been read can rustls say whether it
+            // accepted 0-RTT; checking any earlier would always see the pre-answer default.
+            if matches!(self.state, TlsState::EarlyData { .. }) {
+                let (rejected_pos, offered, buf) =
+                    match std::mem::replace(&mut self.state, TlsState::Stream) {
+                        TlsState::EarlyData { pos, offered, buf } => (pos, offered, buf),
+                        _ => unreachable!(),
+                    };
+                // Accepted: the offered prefix was already sent as 0-RTT, so only the bytes
+                // past it need replaying (they were never actually handed to rustls, since it
+                // stopped offering 0-RTT mid-buffering). Rejected: replay the whole buffer.
+                let mut pos = if self.session.is_early_data_accepted() {
+                    offered
+                } else {
+                    rejected_pos
+                };
+                while pos < buf.len() {
+                    pos += self.session.writer().write(&buf[pos..])?;
+                }
+            }
+
+            while self.session.wants_write() && self.session.is_handshaking() {
+                wrlen += self.write_io().await?;
+            }
+
             match (eof, self.session.is_handshaking()) {
                 (true, true) => {
                     let err = io::Error::new(io::ErrorKind::UnexpectedEof, "tls handshake eof");
@@ -123,7 +267,7 @@ where
 
 impl<IO: AsyncReadRent + AsyncWriteRent, C, SD: SideData> AsyncReadRent for Stream<IO, C>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<SD>>,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
 {
     type ReadFuture<'a, T> = impl Future<Output = BufResult<usize, T>>
     where
@@ -136,6 +280,11 @@ where
     fn read<T: monoio::buf::IoBufMut>(&mut self, mut buf: T) -> Self::ReadFuture<'_, T> {
         let slice = unsafe { std::slice::from_raw_parts_mut(buf.write_ptr(), buf.bytes_total()) };
         async move {
+            // No application data can arrive until the handshake has moved past early data.
+            if matches!(self.state, TlsState::EarlyData { .. }) {
+                return (Err(io::ErrorKind::WouldBlock.into()), buf);
+            }
+
             loop {
                 // read from rustls to buffer
                 match self.session.reader().read(slice) {
@@ -170,18 +319,60 @@ where
         }
     }
 
-    fn readv<T: monoio::buf::IoVecBufMut>(&mut self, buf: T) -> Self::ReadvFuture<'_, T> {
-        // TODO
+    fn readv<T: monoio::buf::IoVecBufMut>(&mut self, mut buf: T) -> Self::ReadvFuture<'_, T> {
         async move {
-            let _ = buf;
-            todo!()
+            // No application data can arrive until the handshake has moved past early data.
+            if matches!(self.state, TlsState::EarlyData { .. }) {
+                return (Err(io::ErrorKind::WouldBlock.into()), buf);
+            }
+
+            let raw = buf.write_iovec_ptr();
+            let len = buf.write_iovec_len();
+            let mut total = 0;
+
+            let mut i = 0;
+            while i < len {
+                let iovec = unsafe { &mut *raw.add(i) };
+                let slice = iovec.as_mut_slice();
+                match self.session.reader().read(slice) {
+                    Ok(n) => {
+                        total += n;
+                        i += 1;
+                    }
+                    // this segment has no plaintext ready; if we already have some bytes for the
+                    // caller, hand them back rather than blocking on the rest of the vector.
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        if total > 0 {
+                            break;
+                        }
+
+                        match self.read_io().await {
+                            Ok(0) => {
+                                return (
+                                    Err(io::Error::new(
+                                        io::ErrorKind::UnexpectedEof,
+                                        "tls raw stream eof",
+                                    )),
+                                    buf,
+                                );
+                            }
+                            Ok(_) => continue,
+                            Err(e) => return (Err(e), buf),
+                        }
+                    }
+                    Err(e) => return (Err(e), buf),
+                }
+            }
+
+            unsafe { buf.set_init(total) };
+            (Ok(total), buf)
         }
     }
 }
 
 impl<IO: AsyncReadRent + AsyncWriteRent, C, SD: SideData> AsyncWriteRent for Stream<IO, C>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<SD>>,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
 {
     type WriteFuture<'a, T> = impl Future<Output = BufResult<usize, T>>
     where
@@ -200,10 +391,40 @@ where
             // construct slice
             let slice = unsafe { std::slice::from_raw_parts(buf.read_ptr(), buf.bytes_init()) };
 
-            // write slice to rustls
-            let n = match self.session.writer().write(slice) {
-                Ok(n) => n,
-                Err(e) => return (Err(e), buf),
+            let n = match &mut self.state {
+                TlsState::EarlyData {
+                    buf: early_buf,
+                    offered,
+                    ..
+                } => match self.session.early_data_writer() {
+                    // offer it as 0-RTT, but keep a copy in case the server rejects it and we
+                    // need to replay through the ordinary writer once the handshake resolves.
+                    Some(mut writer) => match writer.write(slice) {
+                        Ok(n) => {
+                            early_buf.extend_from_slice(&slice[..n]);
+                            *offered += n;
+                            n
+                        }
+                        Err(e) => return (Err(e), buf),
+                    },
+                    // rustls stopped offering 0-RTT (e.g. the ticket's early-data cap was hit):
+                    // these bytes were never handed to it, so they must always be replayed once
+                    // the handshake resolves, whether or not it accepted 0-RTT overall.
+                    None => {
+                        early_buf.extend_from_slice(slice);
+                        slice.len()
+                    }
+                },
+                TlsState::Stream => match self.session.writer().write(slice) {
+                    Ok(n) => n,
+                    Err(e) => return (Err(e), buf),
+                },
+                TlsState::WriteShutdown => {
+                    return (
+                        Err(io::Error::new(io::ErrorKind::BrokenPipe, "tls stream shutdown")),
+                        buf,
+                    )
+                }
             };
 
             // write from rustls to connection
@@ -222,13 +443,56 @@ where
 
     fn writev<T: monoio::buf::IoVecBuf>(&mut self, buf_vec: T) -> Self::WritevFuture<'_, T> {
         async move {
-            let _ = buf_vec;
-            todo!()
+            let raw = buf_vec.read_iovec_ptr();
+            let len = buf_vec.read_iovec_len();
+            let slices: Vec<&[u8]> = (0..len)
+                .map(|i| unsafe { (*raw.add(i)).as_slice() })
+                .collect();
+
+            let n = match &mut self.state {
+                TlsState::EarlyData {
+                    buf: early_buf,
+                    offered,
+                    ..
+                } => {
+                    match write_early_data_vectored(&mut self.session, early_buf, offered, &slices)
+                    {
+                        Ok(n) => n,
+                        Err(e) => return (Err(e), buf_vec),
+                    }
+                }
+                TlsState::Stream => {
+                    // write the whole vector to rustls in one go so it can coalesce it into as
+                    // few TLS records as possible, then flush like the scalar `write` path.
+                    let io_slices: Vec<io::IoSlice> =
+                        slices.iter().map(|s| io::IoSlice::new(s)).collect();
+                    match self.session.writer().write_vectored(&io_slices) {
+                        Ok(n) => n,
+                        Err(e) => return (Err(e), buf_vec),
+                    }
+                }
+                TlsState::WriteShutdown => {
+                    return (
+                        Err(io::Error::new(io::ErrorKind::BrokenPipe, "tls stream shutdown")),
+                        buf_vec,
+                    )
+                }
+            };
+
+            while self.session.wants_write() {
+                match self.write_io().await {
+                    Ok(0) => break,
+                    Ok(_) => (),
+                    Err(e) => return (Err(e), buf_vec),
+                }
+            }
+            (Ok(n), buf_vec)
         }
     }
 
     fn shutdown(&mut self) -> Self::ShutdownFuture<'_> {
         self.session.send_close_notify();
+        self.state = TlsState::WriteShutdown;
         async move {
             while self.session.wants_write() {
                 self.write_io().await?;
@@ -237,3 +501,668 @@ where
         }
     }
 }
+
+// Vectored counterpart of the scalar `write`'s TlsState::EarlyData arm: offers each slice to
+// rustls's early-data writer in turn, falling back to buffering it locally (and every slice
+// after it) once that writer stops accepting 0-RTT, just like the scalar path's `None` arm.
+fn write_early_data_vectored<C: MaybeEarlyData>(
+    session: &mut C,
+    early_buf: &mut Vec<u8>,
+    offered: &mut usize,
+    slices: &[&[u8]],
+) -> io::Result<usize> {
+    let mut total = 0;
+    let mut exhausted = false;
+
+    for slice in slices {
+        if exhausted {
+            early_buf.extend_from_slice(slice);
+            total += slice.len();
+            continue;
+        }
+
+        match session.early_data_writer() {
+            Some(mut writer) => {
+                let n = writer.write(slice)?;
+                early_buf.extend_from_slice(&slice[..n]);
+                *offered += n;
+                total += n;
+                if n < slice.len() {
+                    break;
+                }
+            }
+            None => {
+                early_buf.extend_from_slice(slice);
+                total += slice.len();
+                exhausted = true;
+            }
+        }
+    }
+
+    Ok(total)
+}
+
+async fn flush_pending_alert<IO: AsyncReadRent + AsyncWriteRent, C, SD: SideData>(
+    stream: &mut Stream<IO, C>,
+) where
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+{
+    if let AlertFlush::Deferred(pending) = &stream.alert_flush {
+        if pending.replace(false) {
+            let _ = stream.write_io().await;
+        }
+    }
+}
+
+// Returned by `ReadHalf::reunite` when the two halves didn't come from the same `split()` call.
+pub(crate) struct ReuniteError<IO, C>(pub(crate) ReadHalf<IO, C>, pub(crate) WriteHalf<IO, C>);
+
+// Lets a split half acquire `&mut Stream` fresh for a single call and release it on drop,
+// instead of both halves holding a permanently-live `&mut` over the same `Stream`.
+#[derive(Default)]
+struct SplitLock {
+    locked: Cell<bool>,
+    waiter: Cell<Option<Waker>>,
+}
+
+impl SplitLock {
+    fn lock(self: &Rc<Self>) -> Lock {
+        Lock {
+            lock: self.clone(),
+        }
+    }
+}
+
+struct Lock {
+    lock: Rc<SplitLock>,
+}
+
+impl Future for Lock {
+    type Output = LockGuard;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.lock.locked.replace(true) {
+            self.lock.waiter.set(Some(cx.waker().clone()));
+            Poll::Pending
+        } else {
+            Poll::Ready(LockGuard {
+                lock: self.lock.clone(),
+            })
+        }
+    }
+}
+
+struct LockGuard {
+    lock: Rc<SplitLock>,
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        self.lock.locked.set(false);
+        if let Some(waiter) = self.lock.waiter.take() {
+            waiter.wake();
+        }
+    }
+}
+
+// Locked counterparts of Stream::read_io/write_io/read/readv/write/writev/shutdown, used by the
+// split halves below: each only holds the SplitLock for a single synchronous touch of
+// `session`/`state`, and never across the `do_io` socket wait, so a read blocked on the peer
+// doesn't stall a concurrent write (and vice versa).
+
+async fn split_read_io<IO, C, SD>(shared: *mut Stream<IO, C>, lock: &Rc<SplitLock>) -> io::Result<usize>
+where
+    IO: AsyncReadRent + AsyncWriteRent,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+    SD: SideData,
+{
+    let mut unsafe_read = UnsafeRead::default();
+
+    let n = loop {
+        let result = {
+            let _guard = lock.lock().await;
+            unsafe { (*shared).session.read_tls(&mut unsafe_read) }
+        };
+        match result {
+            Ok(n) => break n,
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                unsafe { unsafe_read.do_io(&mut (*shared).io).await? };
+                continue;
+            }
+            Err(err) => return Err(err),
+        }
+    };
+
+    let _guard = lock.lock().await;
+    let stream = unsafe { &mut *shared };
+    let state = match stream.session.process_new_packets() {
+        Ok(state) => state,
+        Err(err) => {
+            if let AlertFlush::Deferred(pending) = &stream.alert_flush {
+                pending.set(true);
+            }
+            return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+        }
+    };
+
+    if state.peer_has_closed() && stream.session.is_handshaking() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "tls handshake alert",
+        ));
+    }
+
+    Ok(n)
+}
+
+async fn split_write_io<IO, C, SD>(shared: *mut Stream<IO, C>, lock: &Rc<SplitLock>) -> io::Result<usize>
+where
+    IO: AsyncReadRent + AsyncWriteRent,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+    SD: SideData,
+{
+    let mut unsafe_write = UnsafeWrite::default();
+
+    loop {
+        let result = {
+            let _guard = lock.lock().await;
+            unsafe { (*shared).session.write_tls(&mut unsafe_write) }
+        };
+        match result {
+            Ok(n) => return Ok(n),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                unsafe { unsafe_write.do_io(&mut (*shared).io).await? };
+                continue;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+async fn split_flush_pending_alert<IO, C, SD>(shared: *mut Stream<IO, C>, lock: &Rc<SplitLock>)
+where
+    IO: AsyncReadRent + AsyncWriteRent,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+    SD: SideData,
+{
+    let pending = {
+        let _guard = lock.lock().await;
+        match unsafe { &(*shared).alert_flush } {
+            AlertFlush::Deferred(pending) => pending.replace(false),
+            AlertFlush::Inline => false,
+        }
+    };
+    if pending {
+        let _ = split_write_io(shared, lock).await;
+    }
+}
+
+async fn split_read<IO, C, SD, T>(
+    shared: *mut Stream<IO, C>,
+    lock: &Rc<SplitLock>,
+    mut buf: T,
+) -> BufResult<usize, T>
+where
+    IO: AsyncReadRent + AsyncWriteRent,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+    SD: SideData,
+    T: monoio::buf::IoBufMut,
+{
+    {
+        let _guard = lock.lock().await;
+        if matches!(unsafe { &(*shared).state }, TlsState::EarlyData { .. }) {
+            return (Err(io::ErrorKind::WouldBlock.into()), buf);
+        }
+    }
+
+    let slice = unsafe { std::slice::from_raw_parts_mut(buf.write_ptr(), buf.bytes_total()) };
+
+    loop {
+        let attempt = {
+            let _guard = lock.lock().await;
+            unsafe { (*shared).session.reader().read(slice) }
+        };
+        match attempt {
+            Ok(n) => {
+                unsafe { buf.set_init(n) };
+                return (Ok(n), buf);
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => (),
+            Err(e) => return (Err(e), buf),
+        }
+
+        match split_read_io(shared, lock).await {
+            Ok(0) => {
+                return (
+                    Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "tls raw stream eof",
+                    )),
+                    buf,
+                );
+            }
+            Ok(_) => (),
+            Err(e) => return (Err(e), buf),
+        }
+    }
+}
+
+async fn split_readv<IO, C, SD, T>(
+    shared: *mut Stream<IO, C>,
+    lock: &Rc<SplitLock>,
+    mut buf: T,
+) -> BufResult<usize, T>
+where
+    IO: AsyncReadRent + AsyncWriteRent,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+    SD: SideData,
+    T: monoio::buf::IoVecBufMut,
+{
+    {
+        let _guard = lock.lock().await;
+        if matches!(unsafe { &(*shared).state }, TlsState::EarlyData { .. }) {
+            return (Err(io::ErrorKind::WouldBlock.into()), buf);
+        }
+    }
+
+    let raw = buf.write_iovec_ptr();
+    let len = buf.write_iovec_len();
+    let mut total = 0;
+
+    let mut i = 0;
+    while i < len {
+        let iovec = unsafe { &mut *raw.add(i) };
+        let slice = iovec.as_mut_slice();
+        let attempt = {
+            let _guard = lock.lock().await;
+            unsafe { (*shared).session.reader().read(slice) }
+        };
+        match attempt {
+            Ok(n) => {
+                total += n;
+                i += 1;
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if total > 0 {
+                    break;
+                }
+
+                match split_read_io(shared, lock).await {
+                    Ok(0) => {
+                        return (
+                            Err(io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "tls raw stream eof",
+                            )),
+                            buf,
+                        );
+                    }
+                    Ok(_) => continue,
+                    Err(e) => return (Err(e), buf),
+                }
+            }
+            Err(e) => return (Err(e), buf),
+        }
+    }
+
+    unsafe { buf.set_init(total) };
+    (Ok(total), buf)
+}
+
+async fn split_write<IO, C, SD, T>(
+    shared: *mut Stream<IO, C>,
+    lock: &Rc<SplitLock>,
+    buf: T,
+) -> BufResult<usize, T>
+where
+    IO: AsyncReadRent + AsyncWriteRent,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+    SD: SideData,
+    T: monoio::buf::IoBuf,
+{
+    split_flush_pending_alert(shared, lock).await;
+
+    let slice = unsafe { std::slice::from_raw_parts(buf.read_ptr(), buf.bytes_init()) };
+
+    let n = {
+        let _guard = lock.lock().await;
+        let stream = unsafe { &mut *shared };
+        match &mut stream.state {
+            TlsState::EarlyData {
+                buf: early_buf,
+                offered,
+                ..
+            } => match stream.session.early_data_writer() {
+                Some(mut writer) => match writer.write(slice) {
+                    Ok(n) => {
+                        early_buf.extend_from_slice(&slice[..n]);
+                        *offered += n;
+                        n
+                    }
+                    Err(e) => return (Err(e), buf),
+                },
+                None => {
+                    early_buf.extend_from_slice(slice);
+                    slice.len()
+                }
+            },
+            TlsState::Stream => match stream.session.writer().write(slice) {
+                Ok(n) => n,
+                Err(e) => return (Err(e), buf),
+            },
+            TlsState::WriteShutdown => {
+                return (
+                    Err(io::Error::new(io::ErrorKind::BrokenPipe, "tls stream shutdown")),
+                    buf,
+                )
+            }
+        }
+    };
+
+    loop {
+        let wants_write = {
+            let _guard = lock.lock().await;
+            unsafe { (*shared).session.wants_write() }
+        };
+        if !wants_write {
+            break;
+        }
+        match split_write_io(shared, lock).await {
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(e) => return (Err(e), buf),
+        }
+    }
+
+    (Ok(n), buf)
+}
+
+async fn split_writev<IO, C, SD, T>(
+    shared: *mut Stream<IO, C>,
+    lock: &Rc<SplitLock>,
+    buf_vec: T,
+) -> BufResult<usize, T>
+where
+    IO: AsyncReadRent + AsyncWriteRent,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+    SD: SideData,
+    T: monoio::buf::IoVecBuf,
+{
+    split_flush_pending_alert(shared, lock).await;
+
+    let raw = buf_vec.read_iovec_ptr();
+    let len = buf_vec.read_iovec_len();
+    let slices: Vec<&[u8]> = (0..len).map(|i| unsafe { (*raw.add(i)).as_slice() }).collect();
+
+    let n = {
+        let _guard = lock.lock().await;
+        let stream = unsafe { &mut *shared };
+        match &mut stream.state {
+            TlsState::EarlyData {
+                buf: early_buf,
+                offered,
+                ..
+            } => match write_early_data_vectored(&mut stream.session, early_buf, offered, &slices)
+            {
+                Ok(n) => n,
+                Err(e) => return (Err(e), buf_vec),
+            },
+            TlsState::Stream => {
+                let io_slices: Vec<io::IoSlice> =
+                    slices.iter().map(|s| io::IoSlice::new(s)).collect();
+                match stream.session.writer().write_vectored(&io_slices) {
+                    Ok(n) => n,
+                    Err(e) => return (Err(e), buf_vec),
+                }
+            }
+            TlsState::WriteShutdown => {
+                return (
+                    Err(io::Error::new(io::ErrorKind::BrokenPipe, "tls stream shutdown")),
+                    buf_vec,
+                )
+            }
+        }
+    };
+
+    loop {
+        let wants_write = {
+            let _guard = lock.lock().await;
+            unsafe { (*shared).session.wants_write() }
+        };
+        if !wants_write {
+            break;
+        }
+        match split_write_io(shared, lock).await {
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(e) => return (Err(e), buf_vec),
+        }
+    }
+
+    (Ok(n), buf_vec)
+}
+
+async fn split_shutdown<IO, C, SD>(shared: *mut Stream<IO, C>, lock: &Rc<SplitLock>) -> io::Result<()>
+where
+    IO: AsyncReadRent + AsyncWriteRent,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+    SD: SideData,
+{
+    split_flush_pending_alert(shared, lock).await;
+
+    {
+        let _guard = lock.lock().await;
+        let stream = unsafe { &mut *shared };
+        stream.session.send_close_notify();
+        stream.state = TlsState::WriteShutdown;
+    }
+
+    loop {
+        let wants_write = {
+            let _guard = lock.lock().await;
+            unsafe { (*shared).session.wants_write() }
+        };
+        if !wants_write {
+            break;
+        }
+        split_write_io(shared, lock).await?;
+    }
+
+    unsafe { (*shared).io.shutdown().await }
+}
+
+pub(crate) struct ReadHalf<IO, C> {
+    shared: Rc<UnsafeCell<Stream<IO, C>>>,
+    lock: Rc<SplitLock>,
+}
+
+pub(crate) struct WriteHalf<IO, C> {
+    shared: Rc<UnsafeCell<Stream<IO, C>>>,
+    lock: Rc<SplitLock>,
+}
+
+impl<IO, C> ReadHalf<IO, C> {
+    fn raw(&self) -> *mut Stream<IO, C> {
+        self.shared.get()
+    }
+
+    // Recombines the two halves into the original `Stream`, if they came from the same split.
+    pub(crate) fn reunite(
+        self,
+        write: WriteHalf<IO, C>,
+    ) -> Result<Stream<IO, C>, ReuniteError<IO, C>> {
+        if !Rc::ptr_eq(&self.shared, &write.shared) {
+            return Err(ReuniteError(self, write));
+        }
+        drop(write);
+        let mut stream = Rc::try_unwrap(self.shared)
+            .unwrap_or_else(|_| unreachable!("no other handle to a split stream can exist"))
+            .into_inner();
+        stream.alert_flush = AlertFlush::Inline;
+        Ok(stream)
+    }
+}
+
+impl<IO, C> WriteHalf<IO, C> {
+    fn raw(&self) -> *mut Stream<IO, C> {
+        self.shared.get()
+    }
+}
+
+impl<IO: AsyncReadRent + AsyncWriteRent, C, SD: SideData> Stream<IO, C>
+where
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+{
+    // Splits into independent read/write halves for driving from two concurrent tasks, e.g. a
+    // duplex proxy. read_io can't flush an alert itself once split, so that's deferred to the
+    // write half (AlertFlush::Deferred).
+    pub(crate) fn split(mut self) -> (ReadHalf<IO, C>, WriteHalf<IO, C>) {
+        self.alert_flush = AlertFlush::Deferred(Rc::new(Cell::new(false)));
+        let shared = Rc::new(UnsafeCell::new(self));
+        let lock = Rc::new(SplitLock::default());
+        (
+            ReadHalf {
+                shared: shared.clone(),
+                lock: lock.clone(),
+            },
+            WriteHalf { shared, lock },
+        )
+    }
+
+    // Borrowing counterpart of `split`: the halves borrow `self` for `'_` instead of owning it.
+    pub(crate) fn split_mut(&mut self) -> (BorrowedReadHalf<'_, IO, C>, BorrowedWriteHalf<'_, IO, C>) {
+        self.alert_flush = AlertFlush::Deferred(Rc::new(Cell::new(false)));
+        let ptr = self as *mut Stream<IO, C>;
+        let lock = Rc::new(SplitLock::default());
+        (
+            BorrowedReadHalf {
+                ptr,
+                lock: lock.clone(),
+                _marker: PhantomData,
+            },
+            BorrowedWriteHalf {
+                ptr,
+                lock,
+                _marker: PhantomData,
+            },
+        )
+    }
+}
+
+pub(crate) struct BorrowedReadHalf<'a, IO, C> {
+    ptr: *mut Stream<IO, C>,
+    lock: Rc<SplitLock>,
+    _marker: PhantomData<&'a mut Stream<IO, C>>,
+}
+
+pub(crate) struct BorrowedWriteHalf<'a, IO, C> {
+    ptr: *mut Stream<IO, C>,
+    lock: Rc<SplitLock>,
+    _marker: PhantomData<&'a mut Stream<IO, C>>,
+}
+
+impl<'a, IO, C> BorrowedReadHalf<'a, IO, C> {
+    fn raw(&self) -> *mut Stream<IO, C> {
+        self.ptr
+    }
+}
+
+impl<'a, IO, C> BorrowedWriteHalf<'a, IO, C> {
+    fn raw(&self) -> *mut Stream<IO, C> {
+        self.ptr
+    }
+}
+
+// Unlike the owned split()/reunite() pair, split_mut() has no recombining step to reset
+// alert_flush back to Inline, so each half restores it on drop instead. `lock` is already shared
+// by both halves, so its strong count hitting 1 means this is the second (last) half to go.
+impl<'a, IO, C> Drop for BorrowedReadHalf<'a, IO, C> {
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.lock) == 1 {
+            unsafe { (*self.ptr).alert_flush = AlertFlush::Inline };
+        }
+    }
+}
+
+impl<'a, IO, C> Drop for BorrowedWriteHalf<'a, IO, C> {
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.lock) == 1 {
+            unsafe { (*self.ptr).alert_flush = AlertFlush::Inline };
+        }
+    }
+}
+
+macro_rules! impl_read_half {
+    ($ty:ident $(<$lt:lifetime>)?) => {
+        impl<$($lt,)? IO: AsyncReadRent + AsyncWriteRent, C, SD: SideData> AsyncReadRent
+            for $ty<$($lt,)? IO, C>
+        where
+            C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+        {
+            type ReadFuture<'g, T> = impl Future<Output = BufResult<usize, T>>
+            where
+                T: 'g, Self: 'g;
+
+            type ReadvFuture<'g, T> = impl Future<Output = BufResult<usize, T>>
+            where
+                T: 'g, Self: 'g;
+
+            fn read<T: monoio::buf::IoBufMut>(&mut self, buf: T) -> Self::ReadFuture<'_, T> {
+                let lock = self.lock.clone();
+                let ptr = self.raw();
+                async move { split_read(ptr, &lock, buf).await }
+            }
+
+            fn readv<T: monoio::buf::IoVecBufMut>(&mut self, buf: T) -> Self::ReadvFuture<'_, T> {
+                let lock = self.lock.clone();
+                let ptr = self.raw();
+                async move { split_readv(ptr, &lock, buf).await }
+            }
+        }
+    };
+}
+
+macro_rules! impl_write_half {
+    ($ty:ident $(<$lt:lifetime>)?) => {
+        impl<$($lt,)? IO: AsyncReadRent + AsyncWriteRent, C, SD: SideData> AsyncWriteRent
+            for $ty<$($lt,)? IO, C>
+        where
+            C: DerefMut + Deref<Target = ConnectionCommon<SD>> + MaybeEarlyData,
+        {
+            type WriteFuture<'g, T> = impl Future<Output = BufResult<usize, T>>
+            where
+                T: 'g, Self: 'g;
+
+            type WritevFuture<'g, T> = impl Future<Output = BufResult<usize, T>>
+            where
+                T: 'g, Self: 'g;
+
+            type ShutdownFuture<'g> = impl Future<Output = Result<(), std::io::Error>>
+            where
+                Self: 'g;
+
+            fn write<T: monoio::buf::IoBuf>(&mut self, buf: T) -> Self::WriteFuture<'_, T> {
+                let lock = self.lock.clone();
+                let ptr = self.raw();
+                async move { split_write(ptr, &lock, buf).await }
+            }
+
+            fn writev<T: monoio::buf::IoVecBuf>(&mut self, buf_vec: T) -> Self::WritevFuture<'_, T> {
+                let lock = self.lock.clone();
+                let ptr = self.raw();
+                async move { split_writev(ptr, &lock, buf_vec).await }
+            }
+
+            fn shutdown(&mut self) -> Self::ShutdownFuture<'_> {
+                let lock = self.lock.clone();
+                let ptr = self.raw();
+                async move { split_shutdown(ptr, &lock).await }
+            }
+        }
+    };
+}
+
+impl_read_half!(ReadHalf);
+impl_read_half!(BorrowedReadHalf<'a>);
+impl_write_half!(WriteHalf);
+impl_write_half!(BorrowedWriteHalf<'a>);